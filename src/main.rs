@@ -1,6 +1,7 @@
 #![cfg_attr(not(test), no_std)]
 #![cfg_attr(not(test), no_main)]
 #![cfg_attr(test, allow(unused_imports))]
+#![feature(alloc_error_handler)]
 
 use blog_os::println;
 use bootloader::{entry_point, BootInfo};
@@ -12,6 +13,7 @@ entry_point!(kernel_main);
 fn kernel_main(boot_info: &'static BootInfo) -> ! {
     use blog_os::interrupts::PICS;
     use blog_os::memory::{self, create_example_mapping};
+    use x86_64::VirtAddr;
 
     println!("Hello World{}", "!");
 
@@ -20,10 +22,11 @@ fn kernel_main(boot_info: &'static BootInfo) -> ! {
     unsafe { PICS.lock().initialize() };
     x86_64::instructions::interrupts::enable();
 
-    let mut mapper = unsafe { memory::init(boot_info.physical_memory_offset) };
-    let mut frame_allocator = memory::init_frame_allocator(&boot_info.memory_map);
+    let physical_memory_offset = VirtAddr::new(boot_info.physical_memory_offset);
+    unsafe { memory::init(physical_memory_offset, &boot_info.memory_map) };
+    memory::heap::init().expect("heap initialization failed");
 
-    create_example_mapping(&mut mapper, &mut frame_allocator);
+    create_example_mapping();
     unsafe { (0xdeadbeaf900 as *mut u64).write_volatile(0xf021f077f065f04e) };
 
     println!("It did not crash!");
@@ -37,3 +40,9 @@ fn panic(info: &PanicInfo) -> ! {
     println!("{}", info);
     blog_os::hlt_loop();
 }
+
+#[cfg(not(test))]
+#[alloc_error_handler]
+fn alloc_error_handler(layout: core::alloc::Layout) -> ! {
+    panic!("allocation error: {:?}", layout)
+}
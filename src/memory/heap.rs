@@ -0,0 +1,288 @@
+//! A fixed-size kernel heap, backed by a linked-list first-fit allocator.
+
+use super::map_next;
+use alloc::alloc::{GlobalAlloc, Layout};
+use core::mem;
+use x86_64::structures::paging::{mapper::MapToError, Page, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+pub const HEAP_START: usize = 0x_4444_4444_0000;
+pub const HEAP_SIZE: usize = 100 * 1024; // 100 KiB
+
+// Host-side unit tests keep the standard allocator.
+#[cfg_attr(not(test), global_allocator)]
+static ALLOCATOR: Locked<LinkedListAllocator> = Locked::new(LinkedListAllocator::new());
+
+/// Maps the heap region page by page using the global memory manager, then
+/// hands it to the global allocator.
+pub fn init() -> Result<(), MapToError<Size4KiB>> {
+    let heap_start = VirtAddr::new(HEAP_START as u64);
+    let heap_end = heap_start + HEAP_SIZE as u64 - 1u64;
+    let heap_start_page = Page::containing_address(heap_start);
+    let heap_end_page = Page::containing_address(heap_end);
+    let page_range = Page::range_inclusive(heap_start_page, heap_end_page);
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for page in page_range {
+        map_next(page, flags)?.flush();
+    }
+
+    unsafe { ALLOCATOR.lock().init(HEAP_START, HEAP_SIZE) };
+
+    Ok(())
+}
+
+/// A wrapper around `spin::Mutex` so we can implement `GlobalAlloc` for the
+/// allocator type (a blanket `unsafe impl GlobalAlloc for spin::Mutex<T>`
+/// would be an orphan-rule violation, since neither type is ours).
+pub struct Locked<A> {
+    inner: spin::Mutex<A>,
+}
+
+impl<A> Locked<A> {
+    const fn new(inner: A) -> Self {
+        Locked {
+            inner: spin::Mutex::new(inner),
+        }
+    }
+
+    fn lock(&self) -> spin::MutexGuard<A> {
+        self.inner.lock()
+    }
+}
+
+fn align_up(addr: usize, align: usize) -> usize {
+    (addr + align - 1) & !(align - 1)
+}
+
+/// A node in the free list. Lives inside the free memory region it describes.
+struct ListNode {
+    size: usize,
+    next: Option<&'static mut ListNode>,
+}
+
+impl ListNode {
+    const fn new(size: usize) -> Self {
+        ListNode { size, next: None }
+    }
+
+    fn start_addr(&self) -> usize {
+        self as *const Self as usize
+    }
+
+    fn end_addr(&self) -> usize {
+        self.start_addr() + self.size
+    }
+}
+
+/// A first-fit allocator whose free list is a singly linked list of `ListNode`s
+/// kept in address order, so adjacent free regions can be coalesced.
+pub struct LinkedListAllocator {
+    head: ListNode,
+}
+
+impl LinkedListAllocator {
+    pub const fn new() -> Self {
+        LinkedListAllocator {
+            head: ListNode::new(0),
+        }
+    }
+
+    /// Safety: the caller must guarantee that `[heap_start, heap_start + heap_size)`
+    /// is unused and valid, and that this is called only once.
+    pub unsafe fn init(&mut self, heap_start: usize, heap_size: usize) {
+        unsafe { self.add_free_region(heap_start, heap_size) };
+    }
+
+    /// Inserts `[addr, addr + size)` into the free list in address order,
+    /// coalescing it with the regions immediately before and after it when
+    /// they are adjacent.
+    ///
+    /// Safety: the caller must guarantee that the region is unused.
+    unsafe fn add_free_region(&mut self, addr: usize, size: usize) {
+        assert_eq!(align_up(addr, mem::align_of::<ListNode>()), addr);
+        assert!(size >= mem::size_of::<ListNode>());
+
+        let mut current = &mut self.head;
+        while let Some(ref region) = current.next {
+            if region.start_addr() >= addr {
+                break;
+            }
+            current = current.next.as_mut().unwrap();
+        }
+
+        // `current.size == 0` only for the sentinel head, which can never be
+        // adjacent to a real region.
+        let merged_with_prev = current.size != 0 && current.end_addr() == addr;
+        if merged_with_prev {
+            current.size += size;
+        } else {
+            let mut node = ListNode::new(size);
+            node.next = current.next.take();
+            let node_ptr = addr as *mut ListNode;
+            unsafe { node_ptr.write(node) };
+            current.next = Some(unsafe { &mut *node_ptr });
+        }
+
+        let region = if merged_with_prev {
+            current
+        } else {
+            current.next.as_mut().unwrap()
+        };
+        if let Some(next) = region.next.take() {
+            if region.end_addr() == next.start_addr() {
+                region.size += next.size;
+                region.next = next.next;
+            } else {
+                region.next = Some(next);
+            }
+        }
+    }
+
+    /// Looks for a free region that fits a block of `size` with the given
+    /// `align`, removing and returning it (along with the start address of
+    /// the allocation) on success.
+    fn find_region(&mut self, size: usize, align: usize) -> Option<(&'static mut ListNode, usize)> {
+        let mut current = &mut self.head;
+        while let Some(ref mut region) = current.next {
+            if let Ok(alloc_start) = Self::alloc_from_region(region, size, align) {
+                let next = region.next.take();
+                let region = current.next.take().unwrap();
+                current.next = next;
+                return Some((region, alloc_start));
+            } else {
+                current = current.next.as_mut().unwrap();
+            }
+        }
+
+        None
+    }
+
+    /// Checks whether `region` is large enough to hold a `size`-byte,
+    /// `align`-aligned block, leaving either nothing or at least a
+    /// `ListNode`-sized remainder.
+    fn alloc_from_region(region: &ListNode, size: usize, align: usize) -> Result<usize, ()> {
+        let alloc_start = align_up(region.start_addr(), align);
+        let alloc_end = alloc_start.checked_add(size).ok_or(())?;
+
+        if alloc_end > region.end_addr() {
+            return Err(());
+        }
+
+        let excess_size = region.end_addr() - alloc_end;
+        if excess_size > 0 && excess_size < mem::size_of::<ListNode>() {
+            return Err(());
+        }
+
+        Ok(alloc_start)
+    }
+
+    /// Adjusts the given layout so the resulting allocation is also capable
+    /// of storing a `ListNode` once freed.
+    fn size_align(layout: Layout) -> (usize, usize) {
+        let layout = layout
+            .align_to(mem::align_of::<ListNode>())
+            .expect("adjusting alignment failed")
+            .pad_to_align();
+        let size = layout.size().max(mem::size_of::<ListNode>());
+        (size, layout.align())
+    }
+}
+
+unsafe impl GlobalAlloc for Locked<LinkedListAllocator> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let (size, align) = LinkedListAllocator::size_align(layout);
+        let mut allocator = self.lock();
+
+        if let Some((region, alloc_start)) = allocator.find_region(size, align) {
+            let alloc_end = alloc_start.checked_add(size).expect("overflow");
+            let excess_size = region.end_addr() - alloc_end;
+            if excess_size > 0 {
+                unsafe { allocator.add_free_region(alloc_end, excess_size) };
+            }
+            alloc_start as *mut u8
+        } else {
+            core::ptr::null_mut()
+        }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let (size, _) = LinkedListAllocator::size_align(layout);
+        unsafe { self.lock().add_free_region(ptr as usize, size) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A `Locked<LinkedListAllocator>` backed by a heap-allocated (host-side)
+    /// buffer, standing in for the mapped kernel heap region.
+    fn new_heap(size: usize) -> (Locked<LinkedListAllocator>, Vec<u8>) {
+        let mut backing = vec![0u8; size];
+        let allocator = Locked::new(LinkedListAllocator::new());
+        unsafe { allocator.lock().init(backing.as_mut_ptr() as usize, size) };
+        (allocator, backing)
+    }
+
+    #[test]
+    fn alloc_stays_within_the_backing_region() {
+        let (allocator, backing) = new_heap(1024);
+        let layout = Layout::from_size_align(64, 8).unwrap();
+
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!ptr.is_null());
+
+        let heap_start = backing.as_ptr() as usize;
+        let heap_end = heap_start + backing.len();
+        assert!((ptr as usize) >= heap_start);
+        assert!((ptr as usize) + 64 <= heap_end);
+    }
+
+    #[test]
+    fn dealloc_then_alloc_reuses_the_freed_block() {
+        let (allocator, _backing) = new_heap(1024);
+        let layout = Layout::from_size_align(128, 8).unwrap();
+
+        let first = unsafe { allocator.alloc(layout) };
+        unsafe { allocator.dealloc(first, layout) };
+        let second = unsafe { allocator.alloc(layout) };
+
+        assert_eq!(first, second);
+    }
+
+    #[test]
+    fn freeing_two_adjacent_blocks_coalesces_them_into_one_region() {
+        let (allocator, _backing) = new_heap(1024);
+        let half = Layout::from_size_align(64, 8).unwrap();
+        let combined = Layout::from_size_align(128, 8).unwrap();
+
+        let a = unsafe { allocator.alloc(half) };
+        let b = unsafe { allocator.alloc(half) };
+        unsafe { allocator.dealloc(a, half) };
+        unsafe { allocator.dealloc(b, half) };
+
+        // Only possible if the two freed 64-byte regions were merged back
+        // into a single (at least) 128-byte region; first-fit returns the
+        // lowest free address, which is `a`'s.
+        let merged = unsafe { allocator.alloc(combined) };
+        assert_eq!(merged, a);
+    }
+
+    #[test]
+    fn exhausted_heap_returns_null() {
+        let (allocator, _backing) = new_heap(64);
+        let layout = Layout::from_size_align(128, 8).unwrap();
+
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(ptr.is_null());
+    }
+
+    #[test]
+    fn align_up_rounds_up_to_the_next_multiple() {
+        assert_eq!(align_up(0, 8), 0);
+        assert_eq!(align_up(1, 8), 8);
+        assert_eq!(align_up(8, 8), 8);
+        assert_eq!(align_up(9, 8), 16);
+    }
+}
@@ -0,0 +1,354 @@
+pub mod heap;
+pub mod stack;
+
+use bootloader::bootinfo::{MemoryMap, MemoryRegionType};
+use spin::Mutex;
+use x86_64::structures::paging::{
+    mapper::{MapToError, TranslateError, UnmapError},
+    FrameAllocator, FrameDeallocator, Mapper, MapperFlush, OffsetPageTable, Page, PageTable,
+    PageTableFlags, PhysFrame, Size4KiB,
+};
+use x86_64::{PhysAddr, VirtAddr};
+use x86_64::registers::control::Cr3;
+
+/// The global mapper and frame allocator, set up once by `init` and shared by
+/// the `map`/`map_next`/`unmap` functions below so callers no longer have to
+/// thread a mapper and frame allocator through every call site.
+static MAPPER: Mutex<Option<OffsetPageTable<'static>>> = Mutex::new(None);
+static ALLOCATOR: Mutex<Option<BootInfoFrameAllocator>> = Mutex::new(None);
+
+/// Initializes the global mapper and frame allocator from the bootloader's
+/// physical memory offset and memory map.
+///
+/// This must be called exactly once, and only after the physical memory has
+/// been mapped at `physical_memory_offset` (as the bootloader guarantees).
+pub unsafe fn init(physical_memory_offset: VirtAddr, memory_map: &'static MemoryMap) {
+    let level_4_table = unsafe { active_level_4_table(physical_memory_offset) };
+    let mapper = unsafe { OffsetPageTable::new(level_4_table, physical_memory_offset) };
+    let frame_allocator =
+        unsafe { BootInfoFrameAllocator::init(memory_map, physical_memory_offset.as_u64()) };
+
+    *MAPPER.lock() = Some(mapper);
+    *ALLOCATOR.lock() = Some(frame_allocator);
+}
+
+/// Returns a mutable reference to the active level 4 page table.
+///
+/// Safety: the complete physical memory must be mapped at
+/// `physical_memory_offset`, and this function must be called only once to
+/// avoid aliasing `&mut` references to the page table.
+unsafe fn active_level_4_table(physical_memory_offset: VirtAddr) -> &'static mut PageTable {
+    let (level_4_table_frame, _) = Cr3::read();
+
+    let phys = level_4_table_frame.start_address();
+    let virt = physical_memory_offset + phys.as_u64();
+    let page_table_ptr: *mut PageTable = virt.as_mut_ptr();
+
+    unsafe { &mut *page_table_ptr }
+}
+
+/// Maps `page` to `frame` with the given flags, using the global mapper and
+/// frame allocator (the frame allocator may still be needed to allocate
+/// intermediate page tables).
+pub fn map(
+    page: Page,
+    frame: PhysFrame,
+    flags: PageTableFlags,
+) -> Result<MapperFlush<Size4KiB>, MapToError<Size4KiB>> {
+    let mut mapper = MAPPER.lock();
+    let mut allocator = ALLOCATOR.lock();
+    let mapper = mapper.as_mut().expect("memory subsystem not initialized");
+    let allocator = allocator.as_mut().expect("memory subsystem not initialized");
+
+    unsafe { mapper.map_to(page, frame, flags, allocator) }
+}
+
+/// Maps `page` to a freshly allocated frame from the global frame allocator,
+/// with the given flags.
+pub fn map_next(
+    page: Page,
+    flags: PageTableFlags,
+) -> Result<MapperFlush<Size4KiB>, MapToError<Size4KiB>> {
+    let mut mapper = MAPPER.lock();
+    let mut allocator = ALLOCATOR.lock();
+    let mapper = mapper.as_mut().expect("memory subsystem not initialized");
+    let allocator = allocator.as_mut().expect("memory subsystem not initialized");
+
+    let frame = allocator
+        .allocate_frame()
+        .ok_or(MapToError::FrameAllocationFailed)?;
+    unsafe { mapper.map_to(page, frame, flags, allocator) }
+}
+
+/// Unmaps `page` using the global mapper, leaving the backing frame's fate
+/// up to the caller.
+///
+/// Use this for pages mapped to a fixed hardware frame via `map` (such as
+/// the VGA text buffer): that frame is never the global allocator's to give
+/// back, so it must not be passed to `deallocate_frame`. For pages backed by
+/// a frame the global allocator handed out itself (e.g. via `map_next`), use
+/// `unmap_and_free` instead.
+pub fn unmap(page: Page) -> Result<(PhysFrame, MapperFlush<Size4KiB>), UnmapError> {
+    let mut mapper = MAPPER.lock();
+    let mapper = mapper.as_mut().expect("memory subsystem not initialized");
+
+    mapper.unmap(page)
+}
+
+/// Unmaps `page` and returns its backing frame to the global frame
+/// allocator's free list for reuse by a later `allocate_frame`/`map_next`.
+///
+/// Only appropriate for pages backed by a frame the global frame allocator
+/// handed out itself (e.g. via `map_next`); a page mapped to a fixed
+/// hardware frame with `map` (such as the VGA text buffer) must be unmapped
+/// with plain `unmap` instead, since that frame was never the global
+/// allocator's to give back.
+pub fn unmap_and_free(page: Page) -> Result<MapperFlush<Size4KiB>, UnmapError> {
+    let mut mapper = MAPPER.lock();
+    let mut allocator = ALLOCATOR.lock();
+    let mapper = mapper.as_mut().expect("memory subsystem not initialized");
+    let allocator = allocator.as_mut().expect("memory subsystem not initialized");
+
+    let (frame, flush) = mapper.unmap(page)?;
+    unsafe { allocator.deallocate_frame(frame) };
+    Ok(flush)
+}
+
+/// Returns the physical address for the given virtual address, or the
+/// mapper's `TranslateError` if the virtual address is not mapped.
+pub fn translate_addr(
+    addr: u64,
+    mapper: &impl Mapper<Size4KiB>,
+) -> Result<PhysAddr, TranslateError> {
+    let addr = VirtAddr::new(addr);
+    let page: Page = Page::containing_address(addr);
+
+    // perform the translation
+    let frame = mapper.translate_page(page);
+    frame.map(|frame| frame.start_address() + u64::from(addr.page_offset()))
+}
+
+/// Manually walks the four-level page table to translate `addr`, stopping
+/// early (and computing the physical address from the huge frame's base)
+/// when a 1 GiB (P3) or 2 MiB (P2) huge page entry is encountered.
+///
+/// Unlike `translate_addr`, which delegates to `Mapper::translate_page` and
+/// only understands 4 KiB mappings, this is a standalone debugging helper
+/// that future drivers can use when they may encounter huge pages.
+pub fn translate_addr_inner(addr: VirtAddr, physical_memory_offset: u64) -> Option<PhysAddr> {
+    use x86_64::structures::paging::page_table::FrameError;
+
+    const HUGE_PAGE_SIZE_P3: u64 = 1024 * 1024 * 1024; // 1 GiB
+    const HUGE_PAGE_SIZE_P2: u64 = 2 * 1024 * 1024; // 2 MiB
+
+    let (level_4_frame, _) = Cr3::read();
+    let table_indexes = [
+        addr.p4_index(),
+        addr.p3_index(),
+        addr.p2_index(),
+        addr.p1_index(),
+    ];
+    let mut frame = level_4_frame;
+
+    for (level, &index) in table_indexes.iter().enumerate() {
+        let table_virt = VirtAddr::new(physical_memory_offset + frame.start_address().as_u64());
+        let table: &PageTable = unsafe { &*table_virt.as_ptr() };
+        let entry = &table[index];
+
+        // `level` 1 is the P3 table, `level` 2 is the P2 table; an entry
+        // there with the huge-page flag set terminates the walk early.
+        let is_huge_capable_level = level == 1 || level == 2;
+        if is_huge_capable_level && entry.flags().contains(PageTableFlags::HUGE_PAGE) {
+            let huge_page_size = if level == 1 {
+                HUGE_PAGE_SIZE_P3
+            } else {
+                HUGE_PAGE_SIZE_P2
+            };
+            let offset = addr.as_u64() & (huge_page_size - 1);
+            return Some(entry.addr() + offset);
+        }
+
+        frame = match entry.frame() {
+            Ok(frame) => frame,
+            Err(FrameError::FrameNotPresent) => return None,
+            Err(FrameError::HugeFrame) => unreachable!("huge flag checked above"),
+        };
+    }
+
+    Some(frame.start_address() + u64::from(addr.page_offset()))
+}
+
+pub fn create_example_mapping() {
+    use x86_64::structures::paging::PageTableFlags as Flags;
+
+    let page: Page = Page::containing_address(VirtAddr::new(0xdeadbeaf000));
+    let frame = PhysFrame::containing_address(PhysAddr::new(0xb8000));
+    let flags = Flags::PRESENT | Flags::WRITABLE;
+
+    map(page, frame, flags).expect("map_to failed").flush();
+}
+
+/// A FrameAllocator that always returns `None`.
+pub struct EmptyFrameAllocator;
+
+impl FrameAllocator<Size4KiB> for EmptyFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        None
+    }
+}
+
+/// Sentinel written in place of a "next" pointer to mark the end of the free
+/// list. Frame addresses are always 4 KiB-aligned, so `u64::MAX` (which
+/// isn't) can never collide with a real frame address the way plain `0`
+/// could if a usable frame ever started at physical address `0x0`.
+const FREE_LIST_END: u64 = u64::MAX;
+
+/// An intrusive free list of previously deallocated frames, threaded through
+/// the frames' own memory via the physical-memory offset mapping.
+struct FrameFreeList {
+    physical_memory_offset: u64,
+    head: Option<PhysAddr>,
+}
+
+impl FrameFreeList {
+    fn new(physical_memory_offset: u64) -> Self {
+        FrameFreeList {
+            physical_memory_offset,
+            head: None,
+        }
+    }
+
+    fn phys_to_virt(&self, addr: PhysAddr) -> VirtAddr {
+        VirtAddr::new(addr.as_u64() + self.physical_memory_offset)
+    }
+
+    /// Pushes `frame` onto the list by writing the current head into the
+    /// frame's own memory (via the physical memory mapping) and making
+    /// `frame` the new head.
+    ///
+    /// Safety: the caller must guarantee that `frame` is not in use anywhere
+    /// else and that the physical memory mapping covers it.
+    unsafe fn push(&mut self, frame: PhysFrame) {
+        let next = self.head.map(|addr| addr.as_u64()).unwrap_or(FREE_LIST_END);
+        let node_ptr = self.phys_to_virt(frame.start_address()).as_mut_ptr::<u64>();
+        unsafe { node_ptr.write(next) };
+        self.head = Some(frame.start_address());
+    }
+
+    /// Pops the frame at the head of the list, if any, reading the next
+    /// pointer out of its memory.
+    fn pop(&mut self) -> Option<PhysFrame> {
+        let addr = self.head?;
+        let next_ptr = self.phys_to_virt(addr).as_mut_ptr::<u64>();
+        let next = unsafe { next_ptr.read() };
+        self.head = if next == FREE_LIST_END {
+            None
+        } else {
+            Some(PhysAddr::new(next))
+        };
+        Some(PhysFrame::containing_address(addr))
+    }
+}
+
+/// A FrameAllocator that returns usable frames from the bootloader's memory
+/// map, reusing frames that were previously returned via `deallocate_frame`
+/// before falling back to the region iterator.
+pub struct BootInfoFrameAllocator {
+    memory_map: &'static MemoryMap,
+    /// Index of the next unused frame in `usable_frames()`.
+    next: usize,
+    free_list: FrameFreeList,
+}
+
+impl BootInfoFrameAllocator {
+    /// Creates a FrameAllocator from the passed memory map.
+    ///
+    /// `physical_memory_offset` is needed so that the allocator can reach
+    /// freed frames through the physical memory mapping when frames are
+    /// returned via `deallocate_frame`.
+    ///
+    /// Safety: the caller must guarantee that the passed memory map is
+    /// valid, and that all usable frames in it are really unused.
+    pub unsafe fn init(memory_map: &'static MemoryMap, physical_memory_offset: u64) -> Self {
+        BootInfoFrameAllocator {
+            memory_map,
+            next: 0,
+            free_list: FrameFreeList::new(physical_memory_offset),
+        }
+    }
+
+    /// Returns an iterator over the usable frames specified in the memory map.
+    fn usable_frames(&self) -> impl Iterator<Item = PhysFrame> {
+        // get usable regions from memory map
+        let regions = self.memory_map.iter();
+        let usable_regions = regions.filter(|r| r.region_type == MemoryRegionType::Usable);
+        // map each region to its address range
+        let addr_ranges = usable_regions.map(|r| r.range.start_addr()..r.range.end_addr());
+        // transform to an iterator of frame start addresses
+        let frame_addresses = addr_ranges.flat_map(|r| r.step_by(4096));
+        // create `PhysFrame` types from the start addresses
+        frame_addresses.map(|addr| PhysFrame::containing_address(PhysAddr::new(addr)))
+    }
+}
+
+impl FrameAllocator<Size4KiB> for BootInfoFrameAllocator {
+    fn allocate_frame(&mut self) -> Option<PhysFrame> {
+        if let Some(frame) = self.free_list.pop() {
+            return Some(frame);
+        }
+
+        let frame = self.usable_frames().nth(self.next);
+        self.next += 1;
+        frame
+    }
+}
+
+impl FrameDeallocator<Size4KiB> for BootInfoFrameAllocator {
+    /// Safety: the caller must guarantee that `frame` is not in use anywhere
+    /// else and that the physical memory mapping set up by `init` covers it.
+    unsafe fn deallocate_frame(&mut self, frame: PhysFrame) {
+        unsafe { self.free_list.push(frame) };
+    }
+}
+
+#[cfg(test)]
+mod frame_free_list_tests {
+    use super::*;
+
+    #[test]
+    fn push_then_pop_reuses_the_frame() {
+        let mut backing = [0u8; 4096];
+        let mut list = FrameFreeList::new(backing.as_mut_ptr() as u64);
+        let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(0));
+
+        unsafe { list.push(frame) };
+        assert_eq!(list.pop(), Some(frame));
+        assert_eq!(list.pop(), None);
+    }
+
+    #[test]
+    fn pops_in_lifo_order() {
+        let mut backing = [0u8; 2 * 4096];
+        let mut list = FrameFreeList::new(backing.as_mut_ptr() as u64);
+        let first = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(0));
+        let second = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(4096));
+
+        unsafe { list.push(first) };
+        unsafe { list.push(second) };
+
+        assert_eq!(list.pop(), Some(second));
+        assert_eq!(list.pop(), Some(first));
+        assert_eq!(list.pop(), None);
+    }
+
+    #[test]
+    fn a_frame_at_physical_address_zero_is_not_mistaken_for_the_list_end() {
+        let mut backing = [0u8; 4096];
+        let mut list = FrameFreeList::new(backing.as_mut_ptr() as u64);
+        let frame = PhysFrame::<Size4KiB>::containing_address(PhysAddr::new(0));
+
+        unsafe { list.push(frame) };
+        assert_eq!(list.head, Some(PhysAddr::new(0)));
+        assert_eq!(list.pop(), Some(frame));
+    }
+}
@@ -0,0 +1,37 @@
+//! A dedicated kernel stack, mapped with an unmapped guard page below it.
+
+use super::map_next;
+use x86_64::structures::paging::{mapper::MapToError, Page, PageSize, PageTableFlags, Size4KiB};
+use x86_64::VirtAddr;
+
+/// Maps a fresh `num_pages`-page stack starting at `stack_start`, using the
+/// global memory manager, and returns the top-of-stack address the caller
+/// should switch `rsp` to.
+///
+/// The page immediately below `stack_start` is deliberately left unmapped as
+/// a guard page, so that a stack overflow triggers a page fault instead of
+/// silently corrupting whatever memory happens to sit there.
+///
+/// `num_pages` must be at least 1; a zero-page stack would underflow the
+/// inclusive page range below.
+pub fn init_stack(
+    stack_start: VirtAddr,
+    num_pages: u64,
+) -> Result<VirtAddr, MapToError<Size4KiB>> {
+    assert!(num_pages >= 1, "a stack needs at least one page");
+
+    let first_page: Page = Page::containing_address(stack_start);
+    let last_page = first_page + (num_pages - 1);
+    let page_range = Page::range_inclusive(first_page, last_page);
+
+    let flags = PageTableFlags::PRESENT | PageTableFlags::WRITABLE;
+    for page in page_range {
+        map_next(page, flags)?.flush();
+    }
+
+    // Exclusive end of the mapped range: the address one past the last
+    // valid byte, which is what gets loaded into RSP / a TSS stack-table
+    // entry (an inclusive "last byte" address would be odd and violate the
+    // SysV ABI's 16-byte stack alignment as soon as anything is pushed).
+    Ok(last_page.start_address() + Size4KiB::SIZE)
+}